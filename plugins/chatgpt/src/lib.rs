@@ -52,6 +52,21 @@ struct Output {
   response: String
 }
 
+#[host_fn]
+extern "ExtismHost" {
+    fn pg_query(request_json: String) -> String;
+}
+
+#[derive(Serialize)]
+struct QueryRequest {
+    sql: String,
+}
+
+#[derive(Deserialize)]
+struct OrderLookupInput {
+  user_id: i64,
+}
+
 #[plugin_fn]
 pub unsafe fn metadata(_: ()) -> FnResult<Json<PluginMetadata>> {
     let mut parameters = BTreeMap::new();
@@ -105,4 +120,23 @@ pub unsafe fn chatgpt<'a>(input: Vec<u8>) -> FnResult<String> {
 
 }
 
+// Demonstrates calling back into the database through the `pg_query` host function,
+// instead of only transforming the input it was given.
+#[plugin_fn]
+pub unsafe fn order_lookup(input: Vec<u8>) -> FnResult<String> {
+  let input: OrderLookupInput = serde_json::from_slice(&input)?;
+
+  let request = QueryRequest {
+    sql: format!(
+      "select id, total from orders where user_id = {} order by created_at desc limit 5",
+      input.user_id
+    ),
+  };
+
+  let rows_json = unsafe { pg_query(serde_json::to_string(&request)?)? };
+
+  Ok(serde_json::to_string(&Output {
+    response: rows_json,
+  })?)
+}
 
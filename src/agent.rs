@@ -0,0 +1,223 @@
+use crate::{PluginMetadata, Type};
+use extism::Error;
+use pgx::guc::{GucContext, GucFlags, GucRegistry, GucSetting};
+use pgx::prelude::*;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// Maximum number of tool-call round trips `llm_agent` will make before giving up.
+pub static MAX_TOOL_ITERATIONS: GucSetting<i32> = GucSetting::new(8);
+
+/// OpenAI API key used to drive the tool-calling loop. Set this rather than storing a
+/// key in a table, matching how `extism_register` recommends resolving plugin secrets.
+pub static OPENAI_API_KEY: GucSetting<Option<&'static str>> =
+    GucSetting::<Option<&'static str>>::new(None);
+
+pub fn init_guc() {
+    GucRegistry::define_int_guc(
+        "pg_extism.max_tool_iterations",
+        "Maximum number of tool-call round trips llm_agent will make before giving up.",
+        "Bounds the function-calling loop so a model that keeps invoking tools can't run forever.",
+        &MAX_TOOL_ITERATIONS,
+        1,
+        64,
+        GucContext::Userset,
+        GucFlags::default(),
+    );
+
+    GucRegistry::define_string_guc(
+        "pg_extism.openai_apikey",
+        "OpenAI API key used by llm_agent to drive the tool-calling loop.",
+        "Required for llm_agent; unrelated to the per-plugin `openai_apikey` config value \
+         `extism_register` lets individual plugins declare.",
+        &OPENAI_API_KEY,
+        GucContext::Suset,
+        GucFlags::default(),
+    );
+}
+
+fn type_to_json_schema(param_type: &Type) -> Value {
+    match param_type {
+        Type::String => json!({ "type": "string" }),
+        Type::Number => json!({ "type": "number" }),
+        Type::Json => json!({ "type": "object" }),
+        Type::StringArray => json!({ "type": "array", "items": { "type": "string" } }),
+        Type::NumberArray => json!({ "type": "array", "items": { "type": "number" } }),
+        Type::JsonArray => json!({ "type": "array", "items": { "type": "object" } }),
+    }
+}
+
+fn tool_spec(tool_name: &str, metadata: &PluginMetadata) -> Value {
+    let properties: serde_json::Map<String, Value> = metadata
+        .parameters
+        .iter()
+        .map(|(name, param_type)| (name.clone(), type_to_json_schema(param_type)))
+        .collect();
+
+    let required: Vec<&String> = metadata.parameters.keys().collect();
+
+    json!({
+        "type": "function",
+        "function": {
+            "name": tool_name,
+            "description": format!("Calls the `{}` extism plugin.", tool_name),
+            "parameters": {
+                "type": "object",
+                "properties": properties,
+                "required": required,
+            },
+        },
+    })
+}
+
+fn call_tool(defined: &crate::registry::DefinedPlugin, arguments: Value) -> Result<Value, Error> {
+    let manifest = crate::catalog::build_manifest(&defined.path);
+
+    crate::pool::with_plugin(&defined.path, &manifest, |plugin| {
+        let json_string = serde_json::to_string(&arguments).unwrap();
+        let data = plugin.call(&defined.metadata.entry_point, json_string)?;
+        let output = std::str::from_utf8(data).map_err(|e| Error::msg(e.to_string()))?;
+        serde_json::from_str::<Value>(output).map_err(Error::from)
+    })
+}
+
+/// Runs a multi-step OpenAI function-calling loop over the already-`extism_define`d
+/// plugins named in `plugin_names`, letting the model invoke them as tools and reason
+/// over the results. Returns the final assistant message plus the full conversation
+/// trace, once the model replies with plain content and no further tool calls.
+#[pg_extern]
+pub fn llm_agent(prompt: &str, plugin_names: Vec<String>) -> Result<pgx::Json, Error> {
+    let api_key = OPENAI_API_KEY
+        .get()
+        .map(|s| s.to_string())
+        .ok_or_else(|| Error::msg("pg_extism.openai_apikey is not set"))?;
+
+    let mut tools_by_name: HashMap<String, crate::registry::DefinedPlugin> = HashMap::new();
+    let mut tool_specs = Vec::new();
+
+    for name in &plugin_names {
+        let defined = crate::registry::lookup(name).ok_or_else(|| {
+            Error::msg(format!(
+                "plugin `{}` has not been defined via extism_define",
+                name
+            ))
+        })?;
+        tool_specs.push(tool_spec(name, &defined.metadata));
+        tools_by_name.insert(name.clone(), defined);
+    }
+
+    let mut messages = vec![json!({ "role": "user", "content": prompt })];
+    let client = reqwest::blocking::Client::new();
+    let max_iterations = MAX_TOOL_ITERATIONS.get().max(1);
+
+    for _ in 0..max_iterations {
+        let body = json!({
+            "model": "gpt-3.5-turbo",
+            "messages": messages,
+            "tools": tool_specs,
+        });
+
+        let http_response = client
+            .post("https://api.openai.com/v1/chat/completions")
+            .bearer_auth(&api_key)
+            .json(&body)
+            .send()
+            .map_err(|e| Error::msg(e.to_string()))?;
+
+        let status = http_response.status();
+        let response: Value = http_response.json().map_err(|e| Error::msg(e.to_string()))?;
+
+        if let Some(error) = response.get("error") {
+            return Err(Error::msg(format!(
+                "OpenAI request failed ({}): {}",
+                status,
+                error["message"].as_str().unwrap_or(&error.to_string())
+            )));
+        }
+        if !status.is_success() {
+            return Err(Error::msg(format!(
+                "OpenAI request failed with status {}",
+                status
+            )));
+        }
+
+        let message = response["choices"]
+            .get(0)
+            .and_then(|c| c.get("message"))
+            .cloned()
+            .ok_or_else(|| {
+                Error::msg(format!(
+                    "OpenAI response had no choices[0].message: {}",
+                    response
+                ))
+            })?;
+        messages.push(message.clone());
+
+        let tool_calls = message["tool_calls"].as_array().cloned().unwrap_or_default();
+        if tool_calls.is_empty() {
+            return Ok(pgx::Json(json!({
+                "content": message["content"],
+                "trace": messages,
+            })));
+        }
+
+        for call in tool_calls {
+            let tool_call_id = call["id"].as_str().unwrap_or_default().to_string();
+            let tool_name = call["function"]["name"].as_str().unwrap_or_default();
+            let raw_arguments = call["function"]["arguments"].as_str().unwrap_or("{}");
+            let arguments: Value = serde_json::from_str(raw_arguments).unwrap_or_else(|_| json!({}));
+
+            let defined = tools_by_name.get(tool_name).ok_or_else(|| {
+                Error::msg(format!("model called unknown tool `{}`", tool_name))
+            })?;
+
+            let result = call_tool(defined, arguments)?;
+
+            messages.push(json!({
+                "role": "tool",
+                "tool_call_id": tool_call_id,
+                "content": serde_json::to_string(&result).unwrap(),
+            }));
+        }
+    }
+
+    Err(Error::msg(
+        "llm_agent exceeded pg_extism.max_tool_iterations without a final answer",
+    ))
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pgx::pg_schema]
+mod tests {
+    use super::tool_spec;
+    use crate::{PluginMetadata, Type};
+    use pgx::prelude::*;
+    use std::collections::BTreeMap;
+
+    #[pg_test]
+    fn test_llm_agent_rejects_undefined_plugin() {
+        let result = Spi::run("select llm_agent('hello', ARRAY['not_a_defined_plugin']);");
+        assert!(result.is_err());
+    }
+
+    #[pg_test]
+    fn test_tool_spec_describes_plugin_parameters() {
+        let mut parameters = BTreeMap::new();
+        parameters.insert("query".to_string(), Type::String);
+
+        let metadata = PluginMetadata {
+            entry_point: "search".to_string(),
+            parameters,
+            return_type: Type::String,
+            return_field: "response".to_string(),
+            fields: None,
+        };
+
+        let spec = tool_spec("search_plugin", &metadata);
+        assert_eq!(spec["function"]["name"], "search_plugin");
+        assert_eq!(
+            spec["function"]["parameters"]["properties"]["query"]["type"],
+            "string"
+        );
+    }
+}
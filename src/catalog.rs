@@ -0,0 +1,161 @@
+use extism::*;
+use extism_manifest::*;
+use pgx::prelude::*;
+use pgx::{Json, JsonB};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// One row per registered plugin, keyed by the path it's loaded from. `extism_register`
+// upserts into this table; `extism_call`/`extism_define`/`extism_stream` read from it (via
+// `build_manifest`) instead of hardcoding allowed hosts/paths/config.
+pgx::extension_sql!(
+    r#"
+    CREATE TABLE extism_manifests (
+        path TEXT PRIMARY KEY,
+        config JSONB NOT NULL
+    );
+    "#,
+    name = "create_extism_manifests_table",
+);
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ManifestSpec {
+    #[serde(default = "default_allowed_hosts")]
+    pub allowed_hosts: Vec<String>,
+    #[serde(default = "default_allowed_paths")]
+    pub allowed_paths: BTreeMap<String, String>,
+    #[serde(default = "default_memory_pages")]
+    pub memory_pages: u32,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    #[serde(default)]
+    pub config: BTreeMap<String, String>,
+}
+
+fn default_allowed_hosts() -> Vec<String> {
+    vec!["api.openai.com".to_string()]
+}
+
+fn default_allowed_paths() -> BTreeMap<String, String> {
+    BTreeMap::from([("/".to_string(), "/".to_string())])
+}
+
+fn default_memory_pages() -> u32 {
+    5
+}
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+impl Default for ManifestSpec {
+    fn default() -> Self {
+        ManifestSpec {
+            allowed_hosts: default_allowed_hosts(),
+            allowed_paths: default_allowed_paths(),
+            memory_pages: default_memory_pages(),
+            timeout_secs: default_timeout_secs(),
+            config: BTreeMap::from([("openai_apikey".to_string(), "".to_string())]),
+        }
+    }
+}
+
+/// Persists (or replaces) the manifest spec for `path`. Secret config values shouldn't be
+/// stored in plaintext here; use a `"guc:<name>"` value instead, which is resolved against
+/// `current_setting` at manifest-build time (see `resolve_config_value`).
+///
+/// `config` is validated against `ManifestSpec` up front and rejected on a shape mismatch,
+/// rather than being stored as-is and silently falling back to defaults at call time.
+#[pg_extern]
+pub fn extism_register(path: &str, config: Json) -> Result<(), Error> {
+    let spec: ManifestSpec = serde_json::from_value(config.0.clone())
+        .map_err(|e| Error::msg(format!("invalid manifest config for `{}`: {}", path, e)))?;
+    let validated = serde_json::to_value(&spec).unwrap();
+
+    Ok(Spi::run_with_args(
+        "INSERT INTO extism_manifests (path, config) VALUES ($1, $2) \
+         ON CONFLICT (path) DO UPDATE SET config = EXCLUDED.config",
+        Some(vec![
+            (PgBuiltInOids::TEXTOID.oid(), path.into_datum()),
+            (PgBuiltInOids::JSONBOID.oid(), JsonB(validated).into_datum()),
+        ]),
+    )?)
+}
+
+fn load_spec(path: &str) -> ManifestSpec {
+    let row = Spi::get_one_with_args::<JsonB>(
+        "SELECT config FROM extism_manifests WHERE path = $1",
+        vec![(PgBuiltInOids::TEXTOID.oid(), path.into_datum())],
+    );
+
+    match row {
+        Ok(Some(JsonB(value))) => serde_json::from_value(value).unwrap_or_default(),
+        _ => ManifestSpec::default(),
+    }
+}
+
+/// Resolves a config value that names a Postgres GUC (`"guc:pg_extism.openai_apikey"`)
+/// against `current_setting`, so operators can inject secrets without storing them in
+/// `extism_manifests` or recompiling the extension. Plain values pass through unchanged.
+fn resolve_config_value(value: &str) -> String {
+    match value.strip_prefix("guc:") {
+        Some(guc_name) => Spi::get_one_with_args::<String>(
+            "SELECT current_setting($1, true)",
+            vec![(PgBuiltInOids::TEXTOID.oid(), guc_name.into_datum())],
+        )
+        .ok()
+        .flatten()
+        .unwrap_or_default(),
+        None => value.to_string(),
+    }
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pgx::pg_schema]
+mod tests {
+    use pgx::prelude::*;
+
+    #[pg_test]
+    fn test_extism_register_rejects_malformed_config() {
+        let result = Spi::run(
+            "select extism_register('/tmp/plugin.wasm', '{\"memory_pages\": \"not-a-number\"}'::json);",
+        );
+        assert!(result.is_err());
+    }
+
+    #[pg_test]
+    fn test_extism_register_accepts_valid_config() {
+        let result = Spi::run(
+            "select extism_register('/tmp/plugin.wasm', \
+             '{\"allowed_hosts\": [\"example.com\"], \"memory_pages\": 10}'::json);",
+        );
+        assert!(result.is_ok());
+    }
+}
+
+/// Builds the `Manifest` for `path`, using the registered spec if one exists (via
+/// `extism_register`) or the original hardcoded defaults otherwise.
+pub fn build_manifest(path: &str) -> Manifest {
+    let spec = load_spec(path);
+
+    let mut manifest = Manifest::new(vec![Wasm::file(path)])
+        .with_memory_options(MemoryOptions {
+            max_pages: Some(spec.memory_pages),
+        })
+        .with_timeout(std::time::Duration::from_secs(spec.timeout_secs))
+        .with_config(
+            spec.config
+                .iter()
+                .map(|(k, v)| (k.clone(), resolve_config_value(v))),
+        );
+
+    for host in &spec.allowed_hosts {
+        manifest = manifest.with_allowed_host(host);
+    }
+
+    for (src, dst) in &spec.allowed_paths {
+        manifest = manifest.with_allowed_path(src.as_str(), dst.as_str());
+    }
+
+    manifest
+}
@@ -0,0 +1,202 @@
+use extism::{CurrentPlugin, Error, Function, UserData, Val, ValType};
+use pgx::guc::{GucContext, GucFlags, GucRegistry, GucSetting};
+use pgx::prelude::*;
+use serde_json::Value;
+
+/// Whether plugins may run statements that modify data (INSERT/UPDATE/DELETE/DDL)
+/// through `pg_execute`, or are restricted to read-only `pg_query` calls.
+pub static ALLOW_WRITES: GucSetting<bool> = GucSetting::new(false);
+
+/// Comma-separated, case-insensitive statement prefixes plugins are allowed to run,
+/// e.g. `"select,insert into orders"`. Empty means no restriction beyond `allow_writes`.
+pub static STATEMENT_ALLOWLIST: GucSetting<Option<&'static str>> =
+    GucSetting::<Option<&'static str>>::new(None);
+
+/// Timeout, in milliseconds, applied to each SQL statement run via a host function.
+pub static STATEMENT_TIMEOUT_MS: GucSetting<i32> = GucSetting::new(5000);
+
+pub fn init_guc() {
+    GucRegistry::define_bool_guc(
+        "pg_extism.allow_writes",
+        "Allow plugins to run write statements via the pg_execute host function.",
+        "When off (the default), pg_execute is rejected and plugins may only use pg_query.",
+        &ALLOW_WRITES,
+        GucContext::Suset,
+        GucFlags::default(),
+    );
+
+    GucRegistry::define_string_guc(
+        "pg_extism.statement_allowlist",
+        "Comma-separated statement prefixes plugins may run via host functions.",
+        "Case-insensitive prefix match against the trimmed statement text, e.g. \
+         'select,insert into orders'. Empty allows any statement, subject to allow_writes.",
+        &STATEMENT_ALLOWLIST,
+        GucContext::Suset,
+        GucFlags::default(),
+    );
+
+    GucRegistry::define_int_guc(
+        "pg_extism.statement_timeout_ms",
+        "Timeout, in milliseconds, applied to SQL statements run via host functions.",
+        "Enforced with `SET LOCAL statement_timeout` before each host-function call; the \
+         setting stays in effect until the end of the transaction.",
+        &STATEMENT_TIMEOUT_MS,
+        0,
+        3_600_000,
+        GucContext::Suset,
+        GucFlags::default(),
+    );
+}
+
+/// `SPI_execute` (which `client.select`/`client.update` go through) runs every
+/// semicolon-separated statement in the string it's given, not just the first. Without
+/// this, `is_allowed`'s prefix check only has to match the *first* statement, letting a
+/// plugin smuggle e.g. a `delete` past an allowlist meant to restrict it to `select`.
+///
+/// This isn't a real SQL parser — it just refuses any `;` other than one optional
+/// trailing one, so it can reject a semicolon embedded in a string literal as a false
+/// positive. That's the safe direction for a security check to err in.
+fn reject_multiple_statements(sql: &str) -> Result<(), Error> {
+    let trimmed = sql.trim();
+    let body = trimmed.strip_suffix(';').unwrap_or(trimmed);
+
+    if body.contains(';') {
+        return Err(Error::msg(
+            "multi-statement SQL is not permitted via pg_query/pg_execute",
+        ));
+    }
+
+    Ok(())
+}
+
+fn is_allowed(sql: &str, require_read_only: bool) -> Result<(), Error> {
+    reject_multiple_statements(sql)?;
+
+    let trimmed = sql.trim_start().to_lowercase();
+
+    if let Some(allowlist) = STATEMENT_ALLOWLIST.get() {
+        let allowlist = allowlist.to_str().unwrap_or_default();
+        let prefixes: Vec<String> = allowlist
+            .split(',')
+            .map(|prefix| prefix.trim().to_lowercase())
+            .filter(|prefix| !prefix.is_empty())
+            .collect();
+        let allowed = prefixes.iter().any(|prefix| trimmed.starts_with(prefix.as_str()));
+
+        if !prefixes.is_empty() && !allowed {
+            return Err(Error::msg(format!(
+                "statement rejected by pg_extism.statement_allowlist: {}",
+                sql
+            )));
+        }
+    }
+
+    if require_read_only && !(trimmed.starts_with("select") || trimmed.starts_with("with")) {
+        return Err(Error::msg(
+            "pg_execute is disabled; enable pg_extism.allow_writes to permit write statements",
+        ));
+    }
+
+    Ok(())
+}
+
+fn apply_statement_timeout() {
+    let _ = Spi::run(&format!(
+        "SET LOCAL statement_timeout = {}",
+        STATEMENT_TIMEOUT_MS.get()
+    ));
+}
+
+/// `pg_query(sql_json) -> rows_json`: runs a read-only query and returns its rows as a
+/// JSON array of objects. `sql_json` is `{"sql": "..."}`.
+fn pg_query(
+    plugin: &mut CurrentPlugin,
+    inputs: &[Val],
+    outputs: &mut [Val],
+    _user_data: UserData<()>,
+) -> Result<(), Error> {
+    let request: Value = serde_json::from_str(&plugin.memory_get_val::<String>(&inputs[0])?)?;
+    let sql = request["sql"]
+        .as_str()
+        .ok_or_else(|| Error::msg("expected `{\"sql\": \"...\"}`"))?;
+
+    is_allowed(sql, !ALLOW_WRITES.get())?;
+
+    // Reading every column as `Option<String>` only works for text-compatible types;
+    // almost any real table (ints, numerics, bools, timestamps, uuids, ...) would fail
+    // or mangle data. Let Postgres itself do the type-aware conversion by wrapping the
+    // query in `row_to_json`, and read back a single already-JSON column instead of
+    // hand-converting each one.
+    let wrapped_sql = format!(
+        "SELECT row_to_json(pg_extism_row) FROM ({}) pg_extism_row",
+        sql
+    );
+
+    let rows: Result<Vec<Value>, pgx::spi::Error> = Spi::connect(|client| {
+        apply_statement_timeout();
+        let mut rows = Vec::new();
+        let table = client.select(&wrapped_sql, None, None)?;
+        for row in table {
+            let value: Option<pgx::JsonB> = row.get(1)?;
+            rows.push(value.map(|pgx::JsonB(v)| v).unwrap_or(Value::Null));
+        }
+        Ok(rows)
+    });
+
+    let response = serde_json::to_string(&rows.map_err(|e| Error::msg(e.to_string()))?)?;
+    outputs[0] = plugin.memory_new_val(response)?.into();
+    Ok(())
+}
+
+/// `pg_execute(sql_json) -> rowcount`: runs a (potentially write) statement and returns
+/// the number of rows affected as a JSON number. `sql_json` is `{"sql": "..."}`.
+fn pg_execute(
+    plugin: &mut CurrentPlugin,
+    inputs: &[Val],
+    outputs: &mut [Val],
+    _user_data: UserData<()>,
+) -> Result<(), Error> {
+    let request: Value = serde_json::from_str(&plugin.memory_get_val::<String>(&inputs[0])?)?;
+    let sql = request["sql"]
+        .as_str()
+        .ok_or_else(|| Error::msg("expected `{\"sql\": \"...\"}`"))?;
+
+    is_allowed(sql, false)?;
+    if !ALLOW_WRITES.get() {
+        return Err(Error::msg(
+            "pg_execute is disabled; enable pg_extism.allow_writes to permit write statements",
+        ));
+    }
+
+    let rowcount = Spi::connect(|mut client| {
+        apply_statement_timeout();
+        let table = client.update(sql, None, None)?;
+        Ok::<_, pgx::spi::Error>(table.len())
+    })
+    .map_err(|e| Error::msg(e.to_string()))?;
+
+    let response = serde_json::to_string(&rowcount)?;
+    outputs[0] = plugin.memory_new_val(response)?.into();
+    Ok(())
+}
+
+/// Host functions made available to every plugin instance, letting plugins call back
+/// into the database instead of being pure functions over their input.
+pub fn functions() -> Vec<Function> {
+    vec![
+        Function::new(
+            "pg_query",
+            [ValType::PTR],
+            [ValType::PTR],
+            UserData::new(()),
+            pg_query,
+        ),
+        Function::new(
+            "pg_execute",
+            [ValType::PTR],
+            [ValType::PTR],
+            UserData::new(()),
+            pg_execute,
+        ),
+    ]
+}
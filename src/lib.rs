@@ -4,10 +4,25 @@ use pgx::{prelude::*, Json};
 use std::collections::BTreeMap;
 use serde::{Serialize, Deserialize};
 
+mod agent;
+mod catalog;
+mod host_functions;
+mod pool;
+mod registry;
+mod stream;
+
 pgx::pg_module_magic!();
 
+#[allow(non_snake_case)]
+#[pg_guard]
+pub extern "C" fn _PG_init() {
+    pool::init_guc();
+    host_functions::init_guc();
+    agent::init_guc();
+}
+
 #[derive(Debug, Serialize, Deserialize)]
-enum Type {
+pub(crate) enum Type {
     String,
     Number,
     Json,
@@ -17,7 +32,7 @@ enum Type {
 }
 
 #[derive(Serialize, Deserialize)]
-struct PluginMetadata {
+pub(crate) struct PluginMetadata {
     #[serde(rename = "entryPoint")]
     entry_point: String,
     parameters: BTreeMap<String, Type>,
@@ -25,56 +40,89 @@ struct PluginMetadata {
     return_type: Type,
     #[serde(rename = "returnField")]
     return_field: String,
+    /// Present when `returnType` is `JsonArray`: describes the columns to project out of
+    /// each element of `result_json-><returnField>`, turning the generated function into
+    /// a `RETURNS TABLE(...)` instead of a scalar function.
+    #[serde(rename = "fields", default)]
+    fields: Option<BTreeMap<String, Type>>,
 }
 
 #[pg_extern]
 fn extism_call(path: &str, name: &str, input: Json) -> Result<Json, Error> {
     let json_string = serde_json::to_string(&input.0).unwrap();
+    let manifest = catalog::build_manifest(path);
 
-    let ctx = Context::new();
-    let mut plugin = new_plugin(&ctx, path);
+    pool::with_plugin(path, &manifest, |plugin| {
+        let data = match plugin.call(name, json_string.clone()) {
+            Ok(v) => v,
+            Err(e) => error!("Error while calling plugin: {}", e),
+        };
 
-    let data = match plugin.call(name, json_string) {
-        Ok(v) => v,
-        Err(e) => error!("Error while calling plugin: {}", e),
-    };
+        let output = match std::str::from_utf8(data) {
+            Ok(v) => v,
+            Err(e) => error!("Invalid UTF-8 sequence: {}", e),
+        };
 
-    let output = match std::str::from_utf8(data) {
-        Ok(v) => v,
-        Err(e) => error!("Invalid UTF-8 sequence: {}", e),
-    };
+        let response_json: serde_json::Value = serde_json::from_str(output).unwrap();
 
-    let response_json: serde_json::Value = serde_json::from_str(output).unwrap();
-
-    Ok(pgx::Json(response_json))
+        Ok(pgx::Json(response_json))
+    })
 }
 
 #[pg_extern]
 fn extism_define(path: &str, name: &str) -> Result<(), Error> {
-    let ctx = Context::new();
-    let mut plugin = new_plugin(&ctx, path);
+    let manifest = catalog::build_manifest(path);
+
+    let metadata: PluginMetadata = pool::with_plugin(path, &manifest, |plugin| {
+        if !plugin.has_function("metadata") {
+            return Err(error!("Expected a `metadata` function."));
+        }
+
+        let metadata_json = match plugin.call("metadata", "") {
+            Ok(v) => v,
+            Err(err) => return Err(error!("Failed to call metadata function: {}", err)),
+        };
+
+        match serde_json::from_slice(metadata_json) {
+            Ok(v) => Ok(v),
+            Err(err) => Err(error!("Failed to deserialize metadata: {}", err)),
+        }
+    })?;
+
+    let sql = generate_dynamic_function(path, name, &metadata)?;
+    pgx::Spi::run(&sql)?;
+    Ok(registry::record(name, path, &metadata)?)
+}
 
-    if !plugin.has_function("metadata") {
-        return Err(error!("Expected a `metadata` function."));
+/// A bare SQL identifier: letters, digits, underscores, not starting with a digit. Used
+/// to validate plugin-supplied names (parameters, table-mode fields) before splicing
+/// them into generated SQL as identifiers.
+fn is_valid_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
     }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
 
-    let metadata_json = match plugin.call("metadata", "") {
-        Ok(v) => v,
-        Err(err) => return Err(error!("Failed to call metadata function: {}", err)),
-    };
-
-    let metadata : PluginMetadata = match serde_json::from_slice(metadata_json) {
-        Ok(v) => v,
-        Err(err) => return Err(error!("Failed to deserialize metadata: {}", err)),
-    };
+fn generate_dynamic_function(path: &str, name: &str, metadata: &PluginMetadata) -> Result<String, Error> {
+    for param_name in metadata.parameters.keys() {
+        if !is_valid_identifier(param_name) {
+            return Err(error!(
+                "Invalid parameter name `{}` in plugin metadata: must be a simple identifier",
+                param_name
+            ));
+        }
+    }
 
-    let sql = generate_dynamic_function(path, name, &metadata);
-    Ok(pgx::Spi::run(&sql)?)
+    match (&metadata.return_type, &metadata.fields) {
+        (Type::JsonArray, Some(fields)) => generate_table_function(path, name, metadata, fields),
+        _ => Ok(generate_scalar_function(path, name, metadata)),
+    }
 }
 
-fn generate_dynamic_function(path: &str, name: &str, metadata: &PluginMetadata) -> String {
-    let mut sql = format!("CREATE OR REPLACE FUNCTION {}(", name);
-
+fn function_params_sql(metadata: &PluginMetadata) -> String {
     let mut params_sql = Vec::new();
 
     for (param_name, param_type) in &metadata.parameters {
@@ -82,8 +130,23 @@ fn generate_dynamic_function(path: &str, name: &str, metadata: &PluginMetadata)
     }
 
     params_sql.reverse();
+    params_sql.join(", ")
+}
+
+fn input_param_sql(metadata: &PluginMetadata) -> String {
+    let mut params = Vec::new();
+
+    for (param_name, _) in &metadata.parameters {
+        params.push(format!("\t'{}', {}", param_name, param_name));
+    }
+
+    params.join(",\n")
+}
+
+fn generate_scalar_function(path: &str, name: &str, metadata: &PluginMetadata) -> String {
+    let mut sql = format!("CREATE OR REPLACE FUNCTION {}(", name);
 
-    sql.push_str(&params_sql.join(", "));
+    sql.push_str(&function_params_sql(metadata));
     sql.push_str(&format!(
         ") RETURNS {} AS $$\n",
         type_to_sql(&metadata.return_type)
@@ -95,15 +158,7 @@ fn generate_dynamic_function(path: &str, name: &str, metadata: &PluginMetadata)
     sql.push_str("BEGIN\n");
     sql.push_str("    -- Construct JSON object from parameters\n");
     sql.push_str("    input_param := json_build_object(\n");
-
-    let mut params = Vec::new();
-
-    for (param_name, _) in &metadata.parameters {
-        params.push(format!("\t'{}', {}", param_name, param_name));
-    }
-
-    sql.push_str(&params.join(",\n"));
-
+    sql.push_str(&input_param_sql(metadata));
     sql.push_str("\n\t);\n");
     sql.push_str("    -- Call the extism_define function using the provided parameters\n");
     sql.push_str(&format!(
@@ -127,6 +182,81 @@ fn generate_dynamic_function(path: &str, name: &str, metadata: &PluginMetadata)
     sql
 }
 
+/// Generates a `RETURNS TABLE(...)` function for plugins whose `returnField` holds a
+/// JSON array: each element of that array is projected into a row via `fields`, so
+/// `SELECT * FROM my_plugin(...)` yields one row per array element instead of squeezing
+/// list output into a single scalar.
+fn generate_table_function(
+    path: &str,
+    name: &str,
+    metadata: &PluginMetadata,
+    fields: &BTreeMap<String, Type>,
+) -> Result<String, Error> {
+    for field_name in fields.keys() {
+        if !is_valid_identifier(field_name) {
+            return Err(error!(
+                "Invalid field name `{}` in plugin metadata: must be a simple identifier",
+                field_name
+            ));
+        }
+    }
+
+    let mut sql = format!("CREATE OR REPLACE FUNCTION {}(", name);
+
+    sql.push_str(&function_params_sql(metadata));
+
+    let columns_sql: Vec<String> = fields
+        .iter()
+        .map(|(field_name, field_type)| format!("{} {}", field_name, type_to_sql(field_type)))
+        .collect();
+
+    sql.push_str(&format!(") RETURNS TABLE({}) AS $$\n", columns_sql.join(", ")));
+
+    sql.push_str("DECLARE\n");
+    sql.push_str("    result_json json;\n");
+    sql.push_str("    input_param json;\n");
+    sql.push_str("BEGIN\n");
+    sql.push_str("    -- Construct JSON object from parameters\n");
+    sql.push_str("    input_param := json_build_object(\n");
+    sql.push_str(&input_param_sql(metadata));
+    sql.push_str("\n\t);\n");
+    sql.push_str("    -- Call the extism_call function using the provided parameters\n");
+    sql.push_str(&format!(
+        "    SELECT extism_call('{}', '{}', input_param) INTO result_json;\n",
+        path, metadata.entry_point
+    ));
+    sql.push_str("    -- Project each element of the returned array into a row\n");
+    sql.push_str("    RETURN QUERY\n");
+    sql.push_str("    SELECT\n");
+
+    let projections: Vec<String> = fields
+        .iter()
+        .map(|(field_name, field_type)| {
+            format!(
+                "        (elem->>'{}')::{}",
+                field_name,
+                type_to_sql(field_type)
+            )
+        })
+        .collect();
+
+    sql.push_str(&projections.join(",\n"));
+    sql.push_str("\n");
+    sql.push_str(&format!(
+        "    FROM json_array_elements(result_json->'{}') AS elem;\n",
+        metadata.return_field
+    ));
+    sql.push_str("EXCEPTION\n");
+    sql.push_str("    WHEN others THEN\n");
+    sql.push_str("        -- Handle exceptions if necessary\n");
+    sql.push_str("        RAISE NOTICE 'An error occurred: %', SQLERRM;\n");
+    sql.push_str("        RETURN;\n");
+    sql.push_str("END;\n");
+    sql.push_str("$$ LANGUAGE plpgsql;");
+
+    Ok(sql)
+}
+
 fn type_to_sql(param_type: &Type) -> String {
     match param_type {
         Type::Number => "NUMERIC".to_owned(),
@@ -138,23 +268,6 @@ fn type_to_sql(param_type: &Type) -> String {
     }
 }
 
-fn new_plugin<'a>(ctx: &'a Context, path: &'a str) -> Plugin<'a> {
-    let manifest = Manifest::new(vec![Wasm::file(path)])
-        .with_memory_options(MemoryOptions { max_pages: Some(5) })
-        .with_allowed_host("api.openai.com")
-        .with_allowed_path("/", "/")
-        .with_config(
-            vec![(
-                "openai_apikey".to_string(),
-                "".to_string(),
-            )]
-            .into_iter(),
-        )
-        .with_timeout(std::time::Duration::from_secs(10));
-
-    return Plugin::new_with_manifest(ctx, &manifest, [], true).unwrap();
-}
-
 #[cfg(any(test, feature = "pg_test"))]
 #[pg_schema]
 mod tests {
@@ -169,6 +282,69 @@ mod tests {
         let result = Spi::get_one::<i32>("select count_vowels('aaabbb')->'count';");
         assert_eq!(Ok(Some(3)), result);
     }
+
+    #[pg_test]
+    fn test_plugin_pool_recompiles_after_wasm_file_changes() {
+        let path = "/mnt/d/dylibso/pg_extism/src/code.wasm";
+
+        Spi::run(&format!("select extism_define('{}', 'count_vowels_v1');", path)).unwrap();
+        let first = Spi::get_one::<i32>("select count_vowels_v1('aaabbb')->'count';");
+        assert_eq!(Ok(Some(3)), first);
+
+        // Rewriting the same bytes still bumps the file's mtime, so the pool should
+        // treat the cached instance as stale and recompile rather than reuse it.
+        let bytes = std::fs::read(path).unwrap();
+        std::fs::write(path, bytes).unwrap();
+
+        let second = Spi::get_one::<i32>("select count_vowels_v1('aaabbb')->'count';");
+        assert_eq!(Ok(Some(3)), second);
+    }
+
+    #[pg_test]
+    fn test_is_valid_identifier() {
+        assert!(crate::is_valid_identifier("user_id"));
+        assert!(crate::is_valid_identifier("_private"));
+        assert!(!crate::is_valid_identifier("user id"));
+        assert!(!crate::is_valid_identifier("1abc"));
+        assert!(!crate::is_valid_identifier(""));
+        assert!(!crate::is_valid_identifier("name'); DROP TABLE t; --"));
+    }
+
+    #[pg_test]
+    fn test_generate_dynamic_function_rejects_bad_field_name() {
+        let mut fields = std::collections::BTreeMap::new();
+        fields.insert("ok_field".to_string(), crate::Type::String);
+        fields.insert("bad field; DROP TABLE t;--".to_string(), crate::Type::String);
+
+        let metadata = crate::PluginMetadata {
+            entry_point: "search".to_string(),
+            parameters: std::collections::BTreeMap::new(),
+            return_type: crate::Type::JsonArray,
+            return_field: "results".to_string(),
+            fields: Some(fields),
+        };
+
+        let result = crate::generate_dynamic_function("/tmp/plugin.wasm", "search_fn", &metadata);
+        assert!(result.is_err());
+    }
+
+    #[pg_test]
+    fn test_generate_dynamic_function_rejects_bad_parameter_name() {
+        let mut parameters = std::collections::BTreeMap::new();
+        parameters.insert("ok_param".to_string(), crate::Type::String);
+        parameters.insert("bad param\"; --".to_string(), crate::Type::String);
+
+        let metadata = crate::PluginMetadata {
+            entry_point: "greet".to_string(),
+            parameters,
+            return_type: crate::Type::String,
+            return_field: "response".to_string(),
+            fields: None,
+        };
+
+        let result = crate::generate_dynamic_function("/tmp/plugin.wasm", "greet_fn", &metadata);
+        assert!(result.is_err());
+    }
 }
 
 /// This module is required by `cargo pgx test` invocations.
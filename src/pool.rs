@@ -0,0 +1,96 @@
+use extism::*;
+use once_cell::sync::Lazy;
+use pgx::guc::{GucContext, GucFlags, GucRegistry, GucSetting};
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// Maximum number of warm plugin instances kept per (path, config) key.
+pub static MAX_POOL_INSTANCES: GucSetting<i32> = GucSetting::new(4);
+
+pub fn init_guc() {
+    GucRegistry::define_int_guc(
+        "pg_extism.max_pool_instances",
+        "Maximum number of warm plugin instances kept per manifest in the plugin pool.",
+        "Each distinct (path, config) pair gets its own bounded pool of already-instantiated \
+         plugins so that repeated calls reuse a warm instance instead of recompiling the wasm \
+         module from disk.",
+        &MAX_POOL_INSTANCES,
+        1,
+        64,
+        GucContext::Userset,
+        GucFlags::default(),
+    );
+}
+
+type PoolKey = (String, u64);
+
+struct PooledPlugin {
+    plugin: Plugin<'static>,
+    source_mtime: Option<SystemTime>,
+}
+
+// `pub(crate)` so `stream.rs` can build a one-off, unpooled `Plugin<'static>` for
+// streaming calls against the same wasmtime context the pool uses.
+pub(crate) static CONTEXT: Lazy<Context> = Lazy::new(Context::new);
+static POOL: Lazy<Mutex<HashMap<PoolKey, Vec<PooledPlugin>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn config_hash(manifest: &Manifest) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    // The manifest doesn't implement Hash, so hash its canonical JSON form instead.
+    if let Ok(serialized) = serde_json::to_string(manifest) {
+        serialized.hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+fn file_mtime(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Runs `f` against a pooled, already-instantiated plugin for `path`/`manifest`.
+///
+/// On a cache miss, or when the wasm file's mtime no longer matches the cached
+/// instance's, a fresh `Plugin` is compiled and used instead. After `f` returns,
+/// the instance is returned to the pool (bounded by `pg_extism.max_pool_instances`)
+/// so the next call for the same key can skip recompilation entirely.
+pub fn with_plugin<F, R>(path: &str, manifest: &Manifest, f: F) -> Result<R, Error>
+where
+    F: FnOnce(&mut Plugin) -> Result<R, Error>,
+{
+    let key: PoolKey = (path.to_string(), config_hash(manifest));
+    let current_mtime = file_mtime(path);
+
+    let mut pooled = {
+        let mut pool = POOL.lock().unwrap();
+        let bucket = pool.entry(key.clone()).or_insert_with(Vec::new);
+        // Drop any instances left over from a previous version of the wasm file: once
+        // evicted here, a stale entry will never again compete with a fresh one for the
+        // `max_instances` slots checked out below.
+        bucket.retain(|p| p.source_mtime == current_mtime);
+        bucket.pop()
+    };
+
+    if pooled.is_none() {
+        let plugin =
+            Plugin::new_with_manifest(&CONTEXT, manifest, crate::host_functions::functions(), true)?;
+        pooled = Some(PooledPlugin {
+            plugin,
+            source_mtime: current_mtime,
+        });
+    }
+
+    let mut pooled = pooled.unwrap();
+    let result = f(&mut pooled.plugin);
+
+    let mut pool = POOL.lock().unwrap();
+    let bucket = pool.entry(key).or_insert_with(Vec::new);
+    let max_instances = MAX_POOL_INSTANCES.get().max(1) as usize;
+    if bucket.len() < max_instances {
+        bucket.push(pooled);
+    }
+
+    result
+}
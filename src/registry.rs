@@ -0,0 +1,52 @@
+use crate::PluginMetadata;
+use pgx::prelude::*;
+use pgx::JsonB;
+
+// Records the (path, metadata) behind every name `extism_define` has created a SQL
+// function for, so other subsystems (the tool-calling agent) can look a plugin up by
+// the name it was defined under instead of needing the path/entry point again.
+pgx::extension_sql!(
+    r#"
+    CREATE TABLE extism_defined_plugins (
+        name TEXT PRIMARY KEY,
+        path TEXT NOT NULL,
+        metadata JSONB NOT NULL
+    );
+    "#,
+    name = "create_extism_defined_plugins_table",
+    requires = ["create_extism_manifests_table"],
+);
+
+pub struct DefinedPlugin {
+    pub path: String,
+    pub metadata: PluginMetadata,
+}
+
+pub fn record(name: &str, path: &str, metadata: &PluginMetadata) -> Result<(), pgx::spi::Error> {
+    let metadata_json = serde_json::to_value(metadata).unwrap();
+
+    Spi::run_with_args(
+        "INSERT INTO extism_defined_plugins (name, path, metadata) VALUES ($1, $2, $3) \
+         ON CONFLICT (name) DO UPDATE SET path = EXCLUDED.path, metadata = EXCLUDED.metadata",
+        Some(vec![
+            (PgBuiltInOids::TEXTOID.oid(), name.into_datum()),
+            (PgBuiltInOids::TEXTOID.oid(), path.into_datum()),
+            (PgBuiltInOids::JSONBOID.oid(), JsonB(metadata_json).into_datum()),
+        ]),
+    )
+}
+
+pub fn lookup(name: &str) -> Option<DefinedPlugin> {
+    let row = Spi::get_two_with_args::<String, JsonB>(
+        "SELECT path, metadata FROM extism_defined_plugins WHERE name = $1",
+        vec![(PgBuiltInOids::TEXTOID.oid(), name.into_datum())],
+    )
+    .ok()?;
+
+    match row {
+        (Some(path), Some(JsonB(metadata_value))) => serde_json::from_value(metadata_value)
+            .ok()
+            .map(|metadata| DefinedPlugin { path, metadata }),
+        _ => None,
+    }
+}
@@ -0,0 +1,124 @@
+use crate::{catalog, pool};
+use extism::*;
+use pgx::iter::SetOfIterator;
+use pgx::prelude::*;
+use serde_json::Value;
+use std::sync::mpsc::{channel, IntoIter, Sender};
+use std::thread::{self, JoinHandle};
+
+/// Host function a streaming plugin calls once per incremental delta (e.g. one per
+/// `data:` line of an OpenAI `stream: true` response), instead of returning its whole
+/// result at once. `chunk_json` is `{"text": "..."}`.
+fn stream_chunk_function(sender: Sender<String>) -> Function {
+    Function::new(
+        "pg_stream_chunk",
+        [ValType::PTR],
+        [],
+        UserData::new(sender),
+        |plugin, inputs, _outputs, user_data| -> Result<(), Error> {
+            let chunk_json: String = plugin.memory_get_val(&inputs[0])?;
+            let value: Value = serde_json::from_str(&chunk_json)?;
+            let text = value["text"].as_str().unwrap_or_default().to_string();
+
+            let sender = user_data.get()?;
+            let sender = sender.lock().unwrap();
+            let _ = sender.send(text);
+            Ok(())
+        },
+    )
+}
+
+/// Lazily yields chunks as they arrive on `rx`, only joining (and surfacing the result
+/// of) the plugin's background thread once the channel is drained — so a client can
+/// `FETCH` rows while the plugin is still producing them, instead of Postgres only
+/// getting a finished `Vec` after the whole call completes.
+struct StreamRows {
+    rx: IntoIter<String>,
+    handle: Option<JoinHandle<Result<(), String>>>,
+}
+
+impl Iterator for StreamRows {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        if let Some(chunk) = self.rx.next() {
+            return Some(chunk);
+        }
+
+        if let Some(handle) = self.handle.take() {
+            match handle.join() {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => error!("Error while streaming plugin: {}", e),
+                Err(_) => error!("Streaming plugin thread panicked"),
+            }
+        }
+
+        None
+    }
+}
+
+/// Drives `name` in the plugin at `path`, expecting it to report its output through the
+/// `pg_stream_chunk` host function one delta at a time. Each delta becomes its own row,
+/// so a client reading through a cursor can `FETCH` partial output instead of waiting
+/// for the whole response to land.
+///
+/// Unlike `extism_call`/`extism_define`, streaming calls bypass the warm-instance pool:
+/// each call needs its own channel wired into a fresh `Plugin`, so there's nothing to
+/// reuse across invocations. They also don't get the `pg_query`/`pg_execute` host
+/// functions from `host_functions::functions()` — those call back into SPI and `error!`,
+/// neither of which is safe to invoke from any thread but the backend's own, and the
+/// entry point here runs on a dedicated thread so its output can be drained as it's
+/// produced.
+#[pg_extern]
+pub fn extism_stream(
+    path: &str,
+    name: &str,
+    input: Json,
+) -> Result<SetOfIterator<'static, String>, Error> {
+    let json_string = serde_json::to_string(&input.0).unwrap();
+    let manifest = catalog::build_manifest(path);
+
+    let (tx, rx) = channel::<String>();
+
+    let mut plugin =
+        Plugin::new_with_manifest(&pool::CONTEXT, &manifest, [stream_chunk_function(tx)], true)?;
+
+    // Run the (blocking) entry point on its own thread so chunks can be drained from
+    // `rx` as `pg_stream_chunk` sends them, rather than only after the whole call
+    // returns. Its error is captured as a plain `String` and only reported (via
+    // `error!`, on the backend thread) once `StreamRows` has drained the channel.
+    let handle: JoinHandle<Result<(), String>> =
+        thread::spawn(move || plugin.call(name, json_string).map(|_| ()).map_err(|e| e.to_string()));
+
+    Ok(SetOfIterator::new(StreamRows {
+        rx: rx.into_iter(),
+        handle: Some(handle),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StreamRows;
+    use std::sync::mpsc::channel;
+    use std::thread;
+
+    #[test]
+    fn test_stream_rows_yields_chunks_as_they_arrive() {
+        let (tx, rx) = channel::<String>();
+
+        let handle = thread::spawn(move || {
+            for chunk in ["a", "b", "c"] {
+                tx.send(chunk.to_string()).unwrap();
+            }
+            Ok(())
+        });
+
+        let rows = StreamRows {
+            rx: rx.into_iter(),
+            handle: Some(handle),
+        };
+
+        let collected: Vec<String> = rows.collect();
+        assert_eq!(collected, vec!["a", "b", "c"]);
+    }
+}